@@ -15,12 +15,14 @@
 // ================================================================================================
 
 use tokio::sync::mpsc;
-use utils::core::{RaftNode, Storage, KVService};
+use utils::core::{AdminService, RaftNode, Storage, KVService};
 use tonic::transport::Server;
 use std::sync::{Arc, Mutex};
 
 mod utils {
     pub mod core;
+    pub mod error;
+    pub mod transport;
 }
 
 #[tokio::main]
@@ -37,32 +39,49 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Define the gRPC server address and initialize the sled-based storage.
     let addr = format!("127.0.0.1:{}", 50050 + id).parse().unwrap();
+    // The raft transport listens on a separate port from the client-facing gRPC service.
+    let raft_addr = format!("127.0.0.1:{}", 60050 + id).parse().unwrap();
     let storage = Arc::new(Mutex::new(Storage::new(&format!("data_{}", id))));
-    let (raft_tx, _raft_rx) = mpsc::channel(100);
+    let (raft_tx, raft_rx) = mpsc::channel(100);
+    // `RaftNode` lives exclusively on the `run` task below; `KVService`/`AdminService` reach it
+    // through this channel instead of a shared lock held across an `await`.
+    let (command_tx, command_rx) = mpsc::channel(100);
 
     // Initialize the Raft node with the given ID and peer configuration.
-    let raft_node = Arc::new(Mutex::new(RaftNode::new(
+    let mut raft_node = RaftNode::new(
         id,
         peers.clone(),
         raft_tx.clone(),
+        raft_rx,
+        command_rx,
         storage.clone(),
-    )));
+    );
+
+    // Accept inbound Raft traffic from peers and forward it into the run loop below.
+    tokio::spawn(async move {
+        if let Err(e) = utils::transport::serve(raft_addr, raft_tx).await {
+            eprintln!("Raft transport listener failed: {}", e);
+        }
+    });
 
     // Launch the main Raft processing loop in a separate task.
-    let raft_node_cloned = raft_node.clone();
     tokio::spawn(async move {
-        raft_node_cloned.lock().unwrap().run().await;
+        raft_node.run().await;
     });
 
-    // Start the gRPC server for handling client requests.
+    // Start the gRPC server for handling client requests, plus the admin surface used to
+    // bootstrap the cluster and reshape membership at runtime.
     let kv_service = KVService {
-        raft_node: raft_node.clone(),
+        command_tx: command_tx.clone(),
+        storage: storage.clone(),
     };
+    let admin_service = AdminService { command_tx };
 
     println!("KV Service running on {}", addr);
 
     Server::builder()
         .add_service(kv_proto::kv_server::KvServer::new(kv_service))
+        .add_service(kv_proto::admin_server::AdminServer::new(admin_service))
         .serve(addr)
         .await?;
 
@@ -19,133 +19,848 @@
 //
 // ================================================================================================
 
-use sled::{Db, IVec};
-use raft::{prelude::*, storage::MemStorage};
+use sled::{Db, IVec, Tree};
+use raft::{
+    eraftpb::{
+        ConfChange, ConfChangeSingle, ConfChangeType, ConfChangeV2, ConfState, Entry, HardState,
+        Snapshot, SnapshotMetadata,
+    },
+    prelude::*,
+    GetEntriesContext, RaftState, StorageError as RaftStorageError,
+};
+use protobuf::Message as PbMessage;
 use tonic::{Request, Response, Status};
 use async_trait::async_trait;
-use tokio::{
-    net::TcpStream,
-    io::{AsyncReadExt, AsyncWriteExt},
-};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{self, MissedTickBehavior};
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use bincode;
+use serde::{Deserialize, Serialize};
+
+use raft::storage::Storage as RaftStorageTrait;
+
+use crate::utils::error::{self, StorageError};
+use crate::utils::transport;
 
 pub struct Storage {
     db: Db,
+    raft_tree: Tree,
 }
 
 impl Storage {
     pub fn new(path: &str) -> Self {
         let db = sled::open(path).expect("Failed to open sled DB");
-        Self { db }
+        let raft_tree = db.open_tree("raft").expect("Failed to open raft keyspace");
+        Self { db, raft_tree }
+    }
+
+    pub fn set(&self, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        self.db.insert(key, value)?;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<IVec>, StorageError> {
+        Ok(self.db.get(key)?)
     }
 
-    pub fn set(&self, key: &str, value: &[u8]) {
-        self.db.insert(key, value).expect("Failed to insert key-value");
+    pub fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.db.remove(key)?;
+        Ok(())
     }
 
-    pub fn get(&self, key: &str) -> Option<IVec> {
-        self.db.get(key).expect("Failed to get value")
+    pub fn snapshot(&self) -> Result<Vec<u8>, StorageError> {
+        Ok(self.db.export()?.to_vec())
     }
 
-    pub fn delete(&self, key: &str) {
-        self.db.remove(key).expect("Failed to delete key");
+    /// Corrupt or truncated snapshot bytes are recoverable: the database is untouched, so the
+    /// caller can retry with a fresh snapshot instead of the node going down.
+    pub fn load_snapshot(&self, snapshot: &[u8]) -> Result<(), StorageError> {
+        self.db
+            .import(snapshot)
+            .map_err(|e| StorageError::Recoverable(e.to_string()))
     }
 
-    pub fn snapshot(&self) -> Vec<u8> {
-        self.db.export().expect("Failed to create snapshot").to_vec()
+    /// Hands out the raft keyspace so a `SledRaftStorage` can be built on the same DB file.
+    pub fn raft_tree(&self) -> Tree {
+        self.raft_tree.clone()
     }
 
-    pub fn load_snapshot(&self, snapshot: &[u8]) {
-        self.db.import(snapshot).expect("Failed to load snapshot");
+    /// Applies a decoded `Command` to the data tree. `Batch` and `CompareAndSwap` run inside a
+    /// sled transaction so the whole command is all-or-nothing, even when nested inside a
+    /// batch.
+    pub fn apply(&self, command: &Command) -> Result<(), StorageError> {
+        self.db
+            .transaction(|tx_db| Self::apply_in_transaction(tx_db, command))
+            .map_err(|e| StorageError::Fatal(e.to_string()))?;
+        Ok(())
+    }
+
+    fn apply_in_transaction(
+        tx_db: &sled::transaction::TransactionalTree,
+        command: &Command,
+    ) -> sled::transaction::ConflictableTransactionResult<(), sled::Error> {
+        match command {
+            Command::Set { key, value } => {
+                tx_db.insert(key.as_bytes(), value.as_slice())?;
+            }
+            Command::Delete { key } => {
+                tx_db.remove(key.as_bytes())?;
+            }
+            Command::Batch(commands) => {
+                for inner in commands {
+                    Self::apply_in_transaction(tx_db, inner)?;
+                }
+            }
+            Command::CompareAndSwap { key, expected, new } => {
+                let current = tx_db.get(key.as_bytes())?;
+                let matches = match (&current, expected) {
+                    (Some(current), Some(expected)) => current.as_ref() == expected.as_slice(),
+                    (None, None) => true,
+                    _ => false,
+                };
+                if matches {
+                    match new {
+                        Some(value) => {
+                            tx_db.insert(key.as_bytes(), value.as_slice())?;
+                        }
+                        None => {
+                            tx_db.remove(key.as_bytes())?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Opaque payload carried by a Raft proposal. Replaces the earlier whitespace-joined string
+/// protocol (`"SET {key} {value}"`), which silently corrupted any key or value containing
+/// spaces or non-UTF-8 bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Command {
+    Set { key: String, value: Vec<u8> },
+    Delete { key: String },
+    /// Applied as a single atomic sled transaction: either every command in the batch takes
+    /// effect, or none do.
+    Batch(Vec<Command>),
+    /// Sets `key` to `new` only if its current value equals `expected` (`None` means "key must
+    /// be absent"), enabling client-built locks and optimistic concurrency.
+    CompareAndSwap {
+        key: String,
+        expected: Option<Vec<u8>>,
+        new: Option<Vec<u8>>,
+    },
+}
+
+/// `raft::Storage` implementation that persists the log, `HardState` and `ConfState` in a
+/// dedicated sled keyspace so a restarted node recovers with its log intact instead of
+/// starting over with an empty `MemStorage`.
+///
+/// Keys used in the tree:
+/// - `log/{index:020}` -> bincode-encoded `Entry`
+/// - `hard_state`       -> bincode-encoded `HardState`
+/// - `conf_state`       -> bincode-encoded `ConfState`
+/// - `snapshot_meta`    -> bincode-encoded `SnapshotMetadata`
+/// - `snapshot_data`    -> the sled export blob backing the most recent snapshot
+#[derive(Clone)]
+pub struct SledRaftStorage {
+    tree: Tree,
+}
+
+const HARD_STATE_KEY: &str = "hard_state";
+const CONF_STATE_KEY: &str = "conf_state";
+const SNAPSHOT_META_KEY: &str = "snapshot_meta";
+const SNAPSHOT_DATA_KEY: &str = "snapshot_data";
+
+impl SledRaftStorage {
+    pub fn new(tree: Tree) -> Self {
+        Self { tree }
+    }
+
+    fn log_key(index: u64) -> String {
+        format!("log/{:020}", index)
+    }
+
+    fn get_hard_state(&self) -> HardState {
+        self.tree
+            .get(HARD_STATE_KEY)
+            .expect("Failed to read hard state")
+            .map(|bytes| bincode::deserialize(&bytes).expect("Corrupt hard state"))
+            .unwrap_or_default()
+    }
+
+    fn get_conf_state(&self) -> ConfState {
+        self.tree
+            .get(CONF_STATE_KEY)
+            .expect("Failed to read conf state")
+            .map(|bytes| bincode::deserialize(&bytes).expect("Corrupt conf state"))
+            .unwrap_or_default()
+    }
+
+    pub fn set_hard_state(&self, hard_state: &HardState) {
+        let bytes = bincode::serialize(hard_state).expect("Failed to encode hard state");
+        self.tree
+            .insert(HARD_STATE_KEY, bytes)
+            .expect("Failed to persist hard state");
+    }
+
+    pub fn set_conf_state(&self, conf_state: &ConfState) {
+        let bytes = bincode::serialize(conf_state).expect("Failed to encode conf state");
+        self.tree
+            .insert(CONF_STATE_KEY, bytes)
+            .expect("Failed to persist conf state");
+    }
+
+    pub fn append(&self, entries: &[Entry]) {
+        if let Some(first) = entries.first() {
+            // A leader resending part of the log after a conflict always overwrites from
+            // `entries[0].index` on; anything we're holding at or past that index belongs to
+            // the stale term and must go, or it survives as an orphaned tail forever.
+            let stale: Vec<IVec> = self
+                .tree
+                .scan_prefix("log/")
+                .keys()
+                .filter_map(Result::ok)
+                .filter(|key| key.as_ref() >= Self::log_key(first.index).as_bytes())
+                .collect();
+            for key in stale {
+                self.tree
+                    .remove(key)
+                    .expect("Failed to remove conflicting log entry");
+            }
+        }
+        for entry in entries {
+            let bytes = bincode::serialize(entry).expect("Failed to encode log entry");
+            self.tree
+                .insert(Self::log_key(entry.index), bytes)
+                .expect("Failed to persist log entry");
+        }
+    }
+
+    /// The index of the most recently appended entry, or the last snapshot's index if the log
+    /// is empty (0 if there's no snapshot either).
+    pub fn last_index(&self) -> u64 {
+        self.tree
+            .scan_prefix("log/")
+            .last()
+            .transpose()
+            .expect("Failed to scan raft log")
+            .map(|(_, v)| {
+                let entry: Entry = bincode::deserialize(&v).expect("Corrupt log entry");
+                entry.index
+            })
+            .unwrap_or_else(|| self.last_snapshot_index())
+    }
+
+    /// The index of the oldest entry still retained, or one past the last snapshot if
+    /// everything up to (and including) it has been compacted away.
+    pub fn first_index(&self) -> u64 {
+        self.tree
+            .scan_prefix("log/")
+            .next()
+            .transpose()
+            .expect("Failed to scan raft log")
+            .map(|(_, v)| {
+                let entry: Entry = bincode::deserialize(&v).expect("Corrupt log entry");
+                entry.index
+            })
+            .unwrap_or_else(|| self.last_snapshot_index() + 1)
+    }
+
+    fn get_entry(&self, index: u64) -> Option<Entry> {
+        self.tree
+            .get(Self::log_key(index))
+            .expect("Failed to read log entry")
+            .map(|bytes| bincode::deserialize(&bytes).expect("Corrupt log entry"))
+    }
+
+    /// The index covered by the most recent snapshot, or 0 if none has been taken yet.
+    pub fn last_snapshot_index(&self) -> u64 {
+        self.get_snapshot_metadata().map(|m| m.index).unwrap_or(0)
+    }
+
+    fn get_snapshot_metadata(&self) -> Option<SnapshotMetadata> {
+        self.tree
+            .get(SNAPSHOT_META_KEY)
+            .expect("Failed to read snapshot metadata")
+            .map(|bytes| bincode::deserialize(&bytes).expect("Corrupt snapshot metadata"))
+    }
+
+    /// Persists a freshly-built snapshot (metadata + the sled export backing it) and brings
+    /// `ConfState` in line with it, so a restart that lands after this point resumes from the
+    /// snapshot rather than an empty log.
+    pub fn save_snapshot(&self, metadata: &SnapshotMetadata, data: &[u8]) {
+        let meta_bytes = bincode::serialize(metadata).expect("Failed to encode snapshot metadata");
+        self.tree
+            .insert(SNAPSHOT_META_KEY, meta_bytes)
+            .expect("Failed to persist snapshot metadata");
+        self.tree
+            .insert(SNAPSHOT_DATA_KEY, data)
+            .expect("Failed to persist snapshot data");
+        self.set_conf_state(metadata.get_conf_state());
+    }
+
+    pub fn snapshot_data(&self) -> Option<IVec> {
+        self.tree
+            .get(SNAPSHOT_DATA_KEY)
+            .expect("Failed to read snapshot data")
+    }
+
+    /// Removes every log entry strictly before `compact_index`, e.g. after a new snapshot has
+    /// made them redundant.
+    pub fn compact(&self, compact_index: u64) {
+        let boundary = Self::log_key(compact_index).into_bytes();
+        let stale: Vec<IVec> = self
+            .tree
+            .scan_prefix("log/")
+            .keys()
+            .filter_map(Result::ok)
+            .take_while(|key| key.as_ref() < boundary.as_slice())
+            .collect();
+        for key in stale {
+            self.tree
+                .remove(key)
+                .expect("Failed to remove compacted log entry");
+        }
+    }
+
+    /// Drops the entire log, including any entries past the snapshot's index. Unlike `compact`
+    /// (which only trims the prefix a locally-built snapshot made redundant), this is for
+    /// installing an inbound snapshot: the sender built it because our log had already diverged
+    /// or fallen too far behind to catch up by replay, so anything we were holding — before or
+    /// after the snapshot's index — is not trustworthy and must go, mirroring
+    /// `MemStorage::apply_snapshot` in raft-rs.
+    pub fn clear_log(&self) {
+        let keys: Vec<IVec> = self
+            .tree
+            .scan_prefix("log/")
+            .keys()
+            .filter_map(Result::ok)
+            .collect();
+        for key in keys {
+            self.tree
+                .remove(key)
+                .expect("Failed to remove stale log entry");
+        }
+    }
+}
+
+impl raft::storage::Storage for SledRaftStorage {
+    fn initial_state(&self) -> raft::Result<RaftState> {
+        Ok(RaftState {
+            hard_state: self.get_hard_state(),
+            conf_state: self.get_conf_state(),
+        })
+    }
+
+    fn entries(
+        &self,
+        low: u64,
+        high: u64,
+        max_size: impl Into<Option<u64>>,
+        _context: GetEntriesContext,
+    ) -> raft::Result<Vec<Entry>> {
+        if low < self.first_index() {
+            // Already compacted away by a snapshot; the raft::Storage contract requires
+            // `Compacted` here so the caller sends a snapshot instead of retrying `entries`.
+            return Err(RaftStorageError::Compacted.into());
+        }
+        let mut entries = Vec::with_capacity((high - low) as usize);
+        for index in low..high {
+            match self.get_entry(index) {
+                Some(entry) => entries.push(entry),
+                None => return Err(RaftStorageError::Unavailable.into()),
+            }
+        }
+        raft::util::limit_size(&mut entries, max_size.into());
+        Ok(entries)
+    }
+
+    fn term(&self, index: u64) -> raft::Result<u64> {
+        if index == 0 {
+            return Ok(0);
+        }
+        if let Some(entry) = self.get_entry(index) {
+            return Ok(entry.term);
+        }
+        // The entry itself may have been compacted away, but its term is still answerable if
+        // it's exactly the index the last snapshot covers.
+        if let Some(metadata) = self.get_snapshot_metadata() {
+            if metadata.index == index {
+                return Ok(metadata.term);
+            }
+        }
+        if index < self.first_index() {
+            return Err(RaftStorageError::Compacted.into());
+        }
+        Err(RaftStorageError::Unavailable.into())
+    }
+
+    fn first_index(&self) -> raft::Result<u64> {
+        Ok(SledRaftStorage::first_index(self))
+    }
+
+    fn last_index(&self) -> raft::Result<u64> {
+        Ok(SledRaftStorage::last_index(self))
+    }
+
+    fn snapshot(&self, request_index: u64, _to: u64) -> raft::Result<Snapshot> {
+        match self.get_snapshot_metadata() {
+            Some(metadata) if metadata.index >= request_index => {
+                let mut snapshot = Snapshot::default();
+                if let Some(data) = self.snapshot_data() {
+                    snapshot.set_data(data.to_vec());
+                }
+                snapshot.set_metadata(metadata);
+                Ok(snapshot)
+            }
+            _ => Err(RaftStorageError::SnapshotTemporarilyUnavailable.into()),
+        }
     }
 }
 
 pub struct RaftNode {
-    raw_node: RawNode<MemStorage>,
+    id: u64,
+    raw_node: RawNode<SledRaftStorage>,
     storage: Arc<Mutex<Storage>>,
-    peers: Vec<String>,
+    /// Known peer addresses by node id. Unlike the original `Vec<String>` indexed by
+    /// `id - 1`, this map can grow and shrink at runtime as `ConfChange`s are applied.
+    peers: HashMap<u64, String>,
     sender: mpsc::Sender<Message>,
+    /// Inbound Raft messages decoded by the `transport` listener, drained each iteration of
+    /// `run` and stepped into the `RawNode`.
+    raft_rx: mpsc::Receiver<Message>,
+    /// Requests from `KVService`/`AdminService` to act on the `RawNode`, drained each iteration
+    /// of `run` alongside `raft_rx`. `RaftNode` is only ever touched from the `run` task, so
+    /// callers go through this channel instead of a shared lock held across an `await`.
+    command_rx: mpsc::Receiver<RaftCommand>,
+    /// Persistent outbound connections to peers, one per node id.
+    connections: transport::PeerConnections,
+    /// Monotonically-increasing counter used to mint a unique context for each ReadIndex
+    /// request, so responses can be matched back to the waiter that requested them.
+    read_seq: u64,
+    /// Waiters for a ReadIndex context that hasn't come back in `ready.read_states()` yet, plus
+    /// when each one was requested so `sweep_stale_read_waiters` can give up on ones that never
+    /// will — e.g. because leadership changed or there never was a leader to confirm it.
+    read_waiters: HashMap<Vec<u8>, (Instant, oneshot::Sender<()>)>,
+    /// ReadIndex requests that have a known target index but are still waiting for the node's
+    /// applied index to catch up to it, in the order their indices were assigned.
+    pending_reads: VecDeque<(u64, oneshot::Sender<()>)>,
+    /// Tracks how recently each peer has had an unreachable report sent to Raft, so a downed
+    /// peer doesn't flood `report_unreachable` on every failed send.
+    reachability: HashMap<u64, Reachability>,
+    /// Current cluster membership, kept in sync with every applied `ConfChange` so a new
+    /// snapshot's metadata always reflects it.
+    conf_state: ConfState,
+    /// How many entries beyond the last snapshot's index may accumulate before `run` triggers
+    /// another compaction.
+    compaction_threshold: u64,
+}
+
+/// Default for `compaction_threshold`: compact once the applied index has moved this far past
+/// the last snapshot.
+const DEFAULT_COMPACTION_THRESHOLD: u64 = 1000;
+
+/// How long to wait before re-reporting the same peer as unreachable, once it has already been
+/// reported and hasn't delivered anything since.
+const UNREACHABLE_REPORT_BACKOFF: Duration = Duration::from_secs(5);
+
+/// How often `run` calls `RawNode::tick`. This is what actually drives election timeouts and
+/// heartbeats; without it a cluster never elects a leader or keeps one alive.
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a linearizable read waits for its ReadIndex to be confirmed before `run` gives up on
+/// it, e.g. because leadership changed mid-flight or there was no leader to confirm it at all.
+const READ_INDEX_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Everything `KVService`/`AdminService` can ask the `run` loop to do. `RaftNode` lives
+/// exclusively on the `run` task, so these cross a channel rather than a shared lock — a
+/// `Propose` is fire-and-forget (same as before), while the rest carry a reply channel so the
+/// gRPC handler can report success or failure back to the client.
+pub enum RaftCommand {
+    Propose(Vec<u8>),
+    LinearizableRead(oneshot::Sender<()>),
+    Init(oneshot::Sender<raft::Result<()>>),
+    AddNode {
+        node_id: u64,
+        address: String,
+        reply: oneshot::Sender<raft::Result<()>>,
+    },
+    RemoveNode {
+        node_id: u64,
+        reply: oneshot::Sender<raft::Result<()>>,
+    },
+    ChangeMembership {
+        changes: Vec<(u64, Option<String>, ConfChangeType)>,
+        reply: oneshot::Sender<raft::Result<()>>,
+    },
+}
+
+#[derive(Default)]
+struct Reachability {
+    /// When this peer was last reported unreachable, or `None` if it never has been.
+    last_report: Option<Instant>,
+    /// Total number of messages successfully delivered to this peer.
+    delivered: u64,
+    /// Value of `delivered` as of the last unreachable report.
+    delivered_at_last_report: u64,
 }
 
 impl RaftNode {
+    /// `peers` seeds the initial address book (e.g. from argv) but no longer fixes cluster
+    /// membership: nodes are added or removed at runtime via the `Admin` gRPC service.
     pub fn new(
         id: u64,
         peers: Vec<String>,
         sender: mpsc::Sender<Message>,
+        raft_rx: mpsc::Receiver<Message>,
+        command_rx: mpsc::Receiver<RaftCommand>,
         storage: Arc<Mutex<Storage>>,
     ) -> Self {
         let cfg = Config {
             id,
             ..Default::default()
         };
-        let raft_storage = MemStorage::new();
+        let raft_tree = storage.lock().unwrap().raft_tree();
+        let raft_storage = SledRaftStorage::new(raft_tree);
         let raw_node = RawNode::new(&cfg, raft_storage, vec![]).unwrap();
+        let conf_state = raw_node
+            .store()
+            .initial_state()
+            .map(|state| state.conf_state)
+            .unwrap_or_default();
+
+        let peers = peers
+            .into_iter()
+            .enumerate()
+            .map(|(i, addr)| (i as u64 + 1, addr))
+            .collect();
 
         Self {
+            id,
             raw_node,
             storage,
             peers,
             sender,
+            raft_rx,
+            command_rx,
+            connections: transport::PeerConnections::new(),
+            read_seq: 0,
+            read_waiters: HashMap::new(),
+            pending_reads: VecDeque::new(),
+            reachability: HashMap::new(),
+            conf_state,
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
         }
     }
 
+    /// Bootstraps a brand-new cluster by making this node the sole voter and campaigning for
+    /// leadership. This is the standard raft-rs bootstrap dance for a cluster's first node:
+    /// there is no leader yet to commit a conf-change entry through, so the change is applied
+    /// locally instead of proposed.
+    pub fn init(&mut self) -> raft::Result<()> {
+        let mut cc = ConfChange::default();
+        cc.set_change_type(ConfChangeType::AddNode);
+        cc.node_id = self.id;
+        let conf_state = self.raw_node.apply_conf_change(&cc)?;
+        self.raw_node.store().set_conf_state(&conf_state);
+        self.conf_state = conf_state;
+        self.raw_node.campaign()
+    }
+
+    /// Proposes adding a single voter, mirroring it into the local `peers` address book so
+    /// `send_to_peer` can route to it once the change commits.
+    pub fn add_node(&mut self, node_id: u64, address: String) -> raft::Result<()> {
+        let mut cc = ConfChange::default();
+        cc.set_change_type(ConfChangeType::AddNode);
+        cc.node_id = node_id;
+        self.raw_node.propose_conf_change(vec![], cc)?;
+        self.peers.insert(node_id, address);
+        Ok(())
+    }
+
+    pub fn remove_node(&mut self, node_id: u64) -> raft::Result<()> {
+        let mut cc = ConfChange::default();
+        cc.set_change_type(ConfChangeType::RemoveNode);
+        cc.node_id = node_id;
+        self.raw_node.propose_conf_change(vec![], cc)?;
+        self.peers.remove(&node_id);
+        Ok(())
+    }
+
+    /// Applies several membership changes atomically via a single `ConfChangeV2` entry.
+    pub fn change_membership(
+        &mut self,
+        changes: Vec<(u64, Option<String>, ConfChangeType)>,
+    ) -> raft::Result<()> {
+        let mut ccv2 = ConfChangeV2::default();
+        let singles = changes
+            .iter()
+            .map(|(node_id, _, change_type)| {
+                let mut single = ConfChangeSingle::default();
+                single.set_change_type(*change_type);
+                single.node_id = *node_id;
+                single
+            })
+            .collect();
+        ccv2.set_changes(singles);
+        self.raw_node.propose_conf_change(vec![], ccv2)?;
+
+        for (node_id, address, change_type) in changes {
+            match change_type {
+                ConfChangeType::AddNode | ConfChangeType::AddLearnerNode => {
+                    if let Some(address) = address {
+                        self.peers.insert(node_id, address);
+                    }
+                }
+                ConfChangeType::RemoveNode => {
+                    self.peers.remove(&node_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a committed conf-change entry to the raw node and persists the resulting
+    /// `ConfState` so a restart observes the up-to-date membership.
+    fn apply_conf_change_entry(&mut self, entry: &Entry) {
+        let conf_state = match entry.get_entry_type() {
+            EntryType::EntryConfChange => {
+                let cc = ConfChange::parse_from_bytes(&entry.data)
+                    .expect("Failed to decode ConfChange entry");
+                self.raw_node
+                    .apply_conf_change(&cc)
+                    .expect("Failed to apply ConfChange")
+            }
+            EntryType::EntryConfChangeV2 => {
+                let cc = ConfChangeV2::parse_from_bytes(&entry.data)
+                    .expect("Failed to decode ConfChangeV2 entry");
+                self.raw_node
+                    .apply_conf_change(&cc)
+                    .expect("Failed to apply ConfChangeV2")
+            }
+            EntryType::EntryNormal => return,
+        };
+        self.raw_node.store().set_conf_state(&conf_state);
+        self.conf_state = conf_state;
+    }
+
     pub async fn run(&mut self) {
+        let mut tick_interval = time::interval(TICK_INTERVAL);
+        tick_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
         loop {
+            // Wait for whichever happens first: the next tick (drives election timeouts and
+            // heartbeats), an inbound Raft message, or a command from a gRPC handler. Without
+            // this the loop would busy-spin, and without the tick branch specifically, Raft
+            // would never campaign or heartbeat on its own.
+            tokio::select! {
+                _ = tick_interval.tick() => {
+                    self.raw_node.tick();
+                    self.sweep_stale_read_waiters();
+                }
+                Some(msg) = self.raft_rx.recv() => {
+                    self.step(msg);
+                }
+                Some(command) = self.command_rx.recv() => {
+                    self.handle_raft_command(command);
+                }
+            }
+
+            // Drain anything else already queued so a burst of messages/commands is processed
+            // in one pass rather than one `select!` wakeup at a time.
+            while let Ok(msg) = self.raft_rx.try_recv() {
+                self.step(msg);
+            }
+            while let Ok(command) = self.command_rx.try_recv() {
+                self.handle_raft_command(command);
+            }
+
             if self.raw_node.has_ready() {
                 let mut ready = self.raw_node.ready();
 
+                // Persist newly-produced log entries and any updated HardState before they are
+                // acted on, so a crash between here and `advance` still leaves a recoverable log.
+                let raft_storage = self.raw_node.store().clone();
+                raft_storage.append(ready.entries());
+                if let Some(hard_state) = ready.hs() {
+                    raft_storage.set_hard_state(hard_state);
+                }
+
+                // A non-empty snapshot means a peer sent us a full state transfer, e.g. because
+                // we're a newly-added or far-behind follower whose missing entries were already
+                // compacted away on the leader. Rebuild the state machine from it before sending
+                // messages or applying entries this round.
+                if ready.snapshot().get_metadata().index != 0 {
+                    let snapshot = ready.snapshot().clone();
+                    if let Err(e) = self
+                        .storage
+                        .lock()
+                        .unwrap()
+                        .load_snapshot(snapshot.get_data())
+                    {
+                        self.handle_storage_error(e);
+                    } else {
+                        raft_storage.save_snapshot(snapshot.get_metadata(), snapshot.get_data());
+                        self.conf_state = snapshot.get_metadata().get_conf_state().clone();
+                        // The sender only sends a snapshot when our log can't be trusted to
+                        // catch up by replay, so drop all of it — including any entries past
+                        // the snapshot's index — rather than leaving a possibly-conflicting
+                        // tail for `first_index`/`entries`/`term` to keep serving.
+                        raft_storage.clear_log();
+                    }
+                }
+
+                // Persist this round's entries and HardState again: installing a snapshot above
+                // clears the whole log, including the entries appended at the top of this block.
+                // Without a snapshot this rewrites the same keys and changes nothing.
+                raft_storage.append(ready.entries());
+                if let Some(hard_state) = ready.hs() {
+                    raft_storage.set_hard_state(hard_state);
+                }
+
                 for msg in ready.take_messages() {
                     self.send_to_peer(msg).await;
                 }
 
+                // Match each resolved ReadIndex context back to the waiter that requested it;
+                // the read itself is only safe once the applied index below has caught up.
+                for read_state in ready.read_states() {
+                    if let Some((_, tx)) = self.read_waiters.remove(&read_state.request_ctx) {
+                        self.pending_reads.push_back((read_state.index, tx));
+                    }
+                }
+
                 for entry in ready.take_entries() {
-                    if let EntryType::EntryNormal = entry.get_entry_type() {
-                        if !entry.data.is_empty() {
-                            if let Ok(cmd) = String::from_utf8(entry.data.to_vec()) {
-                                self.handle_command(cmd);
+                    match entry.get_entry_type() {
+                        EntryType::EntryNormal => {
+                            if !entry.data.is_empty() {
+                                self.handle_command(&entry.data);
                             }
                         }
+                        EntryType::EntryConfChange | EntryType::EntryConfChangeV2 => {
+                            self.apply_conf_change_entry(&entry);
+                        }
                     }
                 }
 
                 self.raw_node.advance(ready);
+
+                let applied = self.raw_node.raft.raft_log.applied;
+                while let Some(&(index, _)) = self.pending_reads.front() {
+                    if index > applied {
+                        break;
+                    }
+                    let (_, tx) = self.pending_reads.pop_front().unwrap();
+                    let _ = tx.send(());
+                }
+
+                self.maybe_compact(applied);
             }
         }
     }
 
-    pub async fn send_to_peer(&self, msg: Message) {
-        if let Some(peer) = self.peers.get((msg.to as usize) - 1) {
-            if let Ok(mut stream) = TcpStream::connect(peer).await {
-                if let Ok(data) = bincode::serialize(&msg) {
-                    if let Err(e) = stream.write_all(&data).await {
-                        eprintln!("Failed to send message to peer {}: {}", peer, e);
-                    }
+    pub async fn send_to_peer(&mut self, msg: Message) {
+        let to = msg.to;
+        let Some(peer) = self.peers.get(&to).cloned() else {
+            return;
+        };
+
+        match self.connections.send(to, &peer, &msg).await {
+            Ok(()) => {
+                self.reachability.entry(to).or_default().delivered += 1;
+            }
+            Err(e) => {
+                eprintln!("Failed to send message to peer {}: {}", peer, e);
+                self.report_unreachable_if_due(to);
+            }
+        }
+    }
+
+    /// Reports `peer_id` unreachable to Raft, but only if it hasn't already been reported
+    /// recently: either the backoff interval has elapsed since the last report, or at least one
+    /// message was delivered to this peer since the last report (i.e. it just went silent after
+    /// being healthy). This keeps a downed peer from flooding Raft with redundant unreachable
+    /// events on every failed send, while still reacting promptly the moment a
+    /// previously-healthy peer goes silent.
+    fn report_unreachable_if_due(&mut self, peer_id: u64) {
+        let now = Instant::now();
+        let entry = self.reachability.entry(peer_id).or_default();
+
+        let backoff_elapsed = entry
+            .last_report
+            .map_or(true, |last| now.duration_since(last) >= UNREACHABLE_REPORT_BACKOFF);
+        let newly_silent = entry.delivered != entry.delivered_at_last_report;
+
+        if backoff_elapsed || newly_silent {
+            self.raw_node.report_unreachable(peer_id);
+            entry.last_report = Some(now);
+            entry.delivered_at_last_report = entry.delivered;
+        }
+    }
+
+    pub fn handle_command(&mut self, data: &[u8]) {
+        match bincode::deserialize::<Command>(data) {
+            Ok(command) => {
+                let result = self.storage.lock().unwrap().apply(&command);
+                if let Err(e) = result {
+                    self.handle_storage_error(e);
                 }
             }
+            Err(e) => eprintln!("Failed to decode command: {}", e),
         }
     }
 
-    pub fn handle_command(&mut self, cmd: String) {
-        let parts: Vec<&str> = cmd.split_whitespace().collect();
-        if parts.len() < 2 {
+    /// Recoverable storage errors applying a committed entry are logged and skipped — the log
+    /// entry is already committed, so there's nothing to retry. Fatal ones mean the database
+    /// itself is unusable, so the node shuts down cleanly rather than keep serving over a broken
+    /// store.
+    fn handle_storage_error(&self, e: StorageError) {
+        if e.is_fatal() {
+            eprintln!("{}; shutting down", e);
+            std::process::exit(1);
+        } else {
+            eprintln!("{}", e);
+        }
+    }
+
+    /// Once `applied` has moved far enough past the last snapshot, builds a fresh one (a sled
+    /// export plus the applied index/term and current `ConfState`), hands it to the raft
+    /// storage, and compacts the log up to it. This is what keeps the log bounded and lets a
+    /// lagging or newly-added follower catch up via snapshot transfer instead of replay.
+    fn maybe_compact(&mut self, applied: u64) {
+        let raft_storage = self.raw_node.store().clone();
+        let last_snapshot = raft_storage.last_snapshot_index();
+        if applied.saturating_sub(last_snapshot) < self.compaction_threshold {
             return;
         }
-        match parts[0] {
-            "SET" => {
-                if parts.len() == 3 {
-                    self.storage
-                        .lock()
-                        .unwrap()
-                        .set(parts[1], parts[2].as_bytes());
-                }
+
+        let term = match self.raw_node.raft.raft_log.term(applied) {
+            Ok(term) => term,
+            Err(e) => {
+                eprintln!("Failed to look up term for snapshot at index {}: {}", applied, e);
+                return;
             }
-            "DELETE" => {
-                self.storage.lock().unwrap().delete(parts[1]);
+        };
+
+        let data = match self.storage.lock().unwrap().snapshot() {
+            Ok(data) => data,
+            Err(e) => {
+                self.handle_storage_error(e);
+                return;
             }
-            _ => {}
-        }
+        };
+
+        let mut metadata = SnapshotMetadata::default();
+        metadata.index = applied;
+        metadata.term = term;
+        metadata.set_conf_state(self.conf_state.clone());
+
+        raft_storage.save_snapshot(&metadata, &data);
+        raft_storage.compact(applied + 1);
     }
 
     pub fn propose(&mut self, data: Vec<u8>) {
@@ -159,17 +874,76 @@ impl RaftNode {
             eprintln!("Failed to step Raft message: {}", e);
         }
     }
+
+    /// Kicks off a linearizable read via `RawNode::read_index`. `tx` resolves once `run` has
+    /// observed the matching `ReadState` and the node's applied index has caught up to it, at
+    /// which point reading `Storage` directly is safe and up to date.
+    fn start_linearizable_read(&mut self, tx: oneshot::Sender<()>) {
+        self.read_seq += 1;
+        let ctx = self.read_seq.to_be_bytes().to_vec();
+        self.read_waiters.insert(ctx.clone(), (Instant::now(), tx));
+        self.raw_node.read_index(ctx);
+    }
+
+    /// Drops any `read_waiters` entry old enough that its `ReadState` was never going to arrive
+    /// — e.g. leadership changed or there was never a leader to confirm the read index. Dropping
+    /// the `oneshot::Sender` resolves the caller's `rx.await` with an error, which `KVService`
+    /// already turns into `Status::unavailable`, instead of hanging the RPC forever.
+    fn sweep_stale_read_waiters(&mut self) {
+        let now = Instant::now();
+        self.read_waiters
+            .retain(|_, (requested_at, _)| now.duration_since(*requested_at) < READ_INDEX_TIMEOUT);
+    }
+
+    /// Dispatches one request from `command_rx`. Runs on the `run` task, so every branch can
+    /// touch `self` directly; replies are best-effort since a caller can drop its receiver (e.g.
+    /// the client disconnected) before the answer comes back.
+    fn handle_raft_command(&mut self, command: RaftCommand) {
+        match command {
+            RaftCommand::Propose(data) => self.propose(data),
+            RaftCommand::LinearizableRead(tx) => self.start_linearizable_read(tx),
+            RaftCommand::Init(reply) => {
+                let _ = reply.send(self.init());
+            }
+            RaftCommand::AddNode {
+                node_id,
+                address,
+                reply,
+            } => {
+                let _ = reply.send(self.add_node(node_id, address));
+            }
+            RaftCommand::RemoveNode { node_id, reply } => {
+                let _ = reply.send(self.remove_node(node_id));
+            }
+            RaftCommand::ChangeMembership { changes, reply } => {
+                let _ = reply.send(self.change_membership(changes));
+            }
+        }
+    }
 }
 
 pub mod kv_proto {
     tonic::include_proto!("kv");
 }
 
-use kv_proto::{kv_server::Kv, GetRequest, GetResponse, SetRequest, SetResponse};
+use kv_proto::{
+    admin_server::Admin, batch_op::Op as BatchOpKind, kv_server::Kv, AddNodeRequest, BatchRequest,
+    BatchResponse, ChangeMembershipRequest, ChangeMembershipResponse,
+    ChangeType as WireChangeType, CompareAndSwapRequest, CompareAndSwapResponse, GetRequest,
+    GetResponse, InitRequest, InitResponse, RemoveNodeRequest, SetRequest, SetResponse,
+};
+
+/// Channel handle the gRPC services use to reach the `RaftNode` that actually owns the
+/// `RawNode`, since it lives exclusively on the `run` task. A send failure means that task has
+/// exited; `run` never returns, so that only happens if it panicked (e.g. on one of the
+/// `expect`s in `SledRaftStorage`), and it's surfaced to the client as `unavailable`.
+fn command_channel_closed() -> Status {
+    Status::unavailable("Raft node shut down before the request could be handled")
+}
 
-#[derive(Default)]
 pub struct KVService {
-    pub raft_node: Arc<Mutex<RaftNode>>,
+    pub command_tx: mpsc::Sender<RaftCommand>,
+    pub storage: Arc<Mutex<Storage>>,
 }
 
 #[async_trait]
@@ -179,14 +953,15 @@ impl Kv for KVService {
         request: Request<SetRequest>,
     ) -> Result<Response<SetResponse>, Status> {
         let req = request.into_inner();
-        let key = req.key;
-        let value = req.value;
-
-        let cmd = format!("SET {} {}", key, value);
-        self.raft_node
-            .lock()
-            .unwrap()
-            .propose(cmd.into_bytes());
+        let command = Command::Set {
+            key: req.key,
+            value: req.value,
+        };
+        let data = bincode::serialize(&command).expect("Failed to encode command");
+        self.command_tx
+            .send(RaftCommand::Propose(data))
+            .await
+            .map_err(|_| command_channel_closed())?;
 
         Ok(Response::new(SetResponse { success: true }))
     }
@@ -198,20 +973,191 @@ impl Kv for KVService {
         let req = request.into_inner();
         let key = req.key;
 
-        if let Some(value) = self
-            .raft_node
-            .lock()
-            .unwrap()
-            .storage
-            .lock()
-            .unwrap()
-            .get(&key)
-        {
-            Ok(Response::new(GetResponse {
-                value: Some(String::from_utf8(value.to_vec()).unwrap()),
-            }))
-        } else {
-            Ok(Response::new(GetResponse { value: None }))
+        if req.linearizable {
+            let (tx, rx) = oneshot::channel();
+            self.command_tx
+                .send(RaftCommand::LinearizableRead(tx))
+                .await
+                .map_err(|_| command_channel_closed())?;
+            if rx.await.is_err() {
+                return Err(Status::unavailable(
+                    "Read index was not confirmed in time, or the Raft node shut down",
+                ));
+            }
+        }
+
+        let result = self.storage.lock().unwrap().get(&key);
+
+        match result {
+            Ok(value) => Ok(Response::new(GetResponse {
+                value: value.map(|v| v.to_vec()),
+            })),
+            Err(e) => {
+                let fatal = e.is_fatal();
+                let status = error::to_status(&e);
+                if fatal {
+                    eprintln!("{}; shutting down", e);
+                    std::process::exit(1);
+                }
+                Err(status)
+            }
+        }
+    }
+
+    async fn batch(
+        &self,
+        request: Request<BatchRequest>,
+    ) -> Result<Response<BatchResponse>, Status> {
+        let req = request.into_inner();
+        let commands = req
+            .ops
+            .into_iter()
+            .filter_map(|op| match op.op {
+                Some(BatchOpKind::Set(set)) => Some(Command::Set {
+                    key: set.key,
+                    value: set.value,
+                }),
+                Some(BatchOpKind::Delete(delete)) => Some(Command::Delete { key: delete.key }),
+                None => None,
+            })
+            .collect();
+
+        let data =
+            bincode::serialize(&Command::Batch(commands)).expect("Failed to encode command");
+        self.command_tx
+            .send(RaftCommand::Propose(data))
+            .await
+            .map_err(|_| command_channel_closed())?;
+
+        Ok(Response::new(BatchResponse { success: true }))
+    }
+
+    async fn compare_and_swap(
+        &self,
+        request: Request<CompareAndSwapRequest>,
+    ) -> Result<Response<CompareAndSwapResponse>, Status> {
+        let req = request.into_inner();
+        let command = Command::CompareAndSwap {
+            key: req.key,
+            expected: req.expected,
+            new: req.new,
+        };
+        let data = bincode::serialize(&command).expect("Failed to encode command");
+        self.command_tx
+            .send(RaftCommand::Propose(data))
+            .await
+            .map_err(|_| command_channel_closed())?;
+
+        Ok(Response::new(CompareAndSwapResponse { success: true }))
+    }
+}
+
+/// gRPC surface for reshaping cluster membership at runtime, since nodes are no longer fixed
+/// at process start.
+pub struct AdminService {
+    pub command_tx: mpsc::Sender<RaftCommand>,
+}
+
+#[async_trait]
+impl Admin for AdminService {
+    async fn init(
+        &self,
+        _request: Request<InitRequest>,
+    ) -> Result<Response<InitResponse>, Status> {
+        let (reply, rx) = oneshot::channel();
+        self.command_tx
+            .send(RaftCommand::Init(reply))
+            .await
+            .map_err(|_| command_channel_closed())?;
+        match rx.await.map_err(|_| command_channel_closed())? {
+            Ok(()) => Ok(Response::new(InitResponse { success: true })),
+            Err(e) => Err(Status::internal(format!("Failed to init cluster: {}", e))),
+        }
+    }
+
+    async fn add_node(
+        &self,
+        request: Request<AddNodeRequest>,
+    ) -> Result<Response<ChangeMembershipResponse>, Status> {
+        let req = request.into_inner();
+        let (reply, rx) = oneshot::channel();
+        self.command_tx
+            .send(RaftCommand::AddNode {
+                node_id: req.node_id,
+                address: req.address,
+                reply,
+            })
+            .await
+            .map_err(|_| command_channel_closed())?;
+        match rx.await.map_err(|_| command_channel_closed())? {
+            Ok(()) => Ok(Response::new(ChangeMembershipResponse {
+                success: true,
+                error: String::new(),
+            })),
+            Err(e) => Ok(Response::new(ChangeMembershipResponse {
+                success: false,
+                error: e.to_string(),
+            })),
+        }
+    }
+
+    async fn remove_node(
+        &self,
+        request: Request<RemoveNodeRequest>,
+    ) -> Result<Response<ChangeMembershipResponse>, Status> {
+        let req = request.into_inner();
+        let (reply, rx) = oneshot::channel();
+        self.command_tx
+            .send(RaftCommand::RemoveNode {
+                node_id: req.node_id,
+                reply,
+            })
+            .await
+            .map_err(|_| command_channel_closed())?;
+        match rx.await.map_err(|_| command_channel_closed())? {
+            Ok(()) => Ok(Response::new(ChangeMembershipResponse {
+                success: true,
+                error: String::new(),
+            })),
+            Err(e) => Ok(Response::new(ChangeMembershipResponse {
+                success: false,
+                error: e.to_string(),
+            })),
+        }
+    }
+
+    async fn change_membership(
+        &self,
+        request: Request<ChangeMembershipRequest>,
+    ) -> Result<Response<ChangeMembershipResponse>, Status> {
+        let req = request.into_inner();
+        let changes = req
+            .changes
+            .into_iter()
+            .map(|change| {
+                let change_type = match WireChangeType::try_from(change.change_type) {
+                    Ok(WireChangeType::AddNode) => ConfChangeType::AddNode,
+                    Ok(WireChangeType::RemoveNode) => ConfChangeType::RemoveNode,
+                    Err(_) => ConfChangeType::AddNode,
+                };
+                (change.node_id, Some(change.address), change_type)
+            })
+            .collect();
+
+        let (reply, rx) = oneshot::channel();
+        self.command_tx
+            .send(RaftCommand::ChangeMembership { changes, reply })
+            .await
+            .map_err(|_| command_channel_closed())?;
+        match rx.await.map_err(|_| command_channel_closed())? {
+            Ok(()) => Ok(Response::new(ChangeMembershipResponse {
+                success: true,
+                error: String::new(),
+            })),
+            Err(e) => Ok(Response::new(ChangeMembershipResponse {
+                success: false,
+                error: e.to_string(),
+            })),
         }
     }
 }
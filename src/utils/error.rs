@@ -0,0 +1,56 @@
+// ================================================================================================
+// Storage error types. Every `Storage` method used to unwrap with `.expect(...)`, so a transient
+// sled I/O hiccup or a corrupt import took the whole node down and surfaced to gRPC clients as a
+// dropped connection rather than a status code. `StorageError` distinguishes errors that mean the
+// database itself is unusable (fatal: the node should shut down cleanly) from ones where the
+// operation failed but the database is still sound (recoverable: worth a `tonic::Status`, not a
+// crash).
+// ================================================================================================
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum StorageError {
+    /// The sled database itself is unusable (disk/DB-level failure) — the node can't keep
+    /// serving traffic safely and should shut down cleanly rather than limp along.
+    Fatal(String),
+    /// The operation itself failed but the database is still sound, e.g. a corrupt snapshot
+    /// import or a required key that's missing.
+    Recoverable(String),
+}
+
+impl StorageError {
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, StorageError::Fatal(_))
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Fatal(msg) => write!(f, "fatal storage error: {}", msg),
+            StorageError::Recoverable(msg) => write!(f, "storage error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<sled::Error> for StorageError {
+    fn from(e: sled::Error) -> Self {
+        // A bare `sled::Error` surfacing from a plain get/insert/remove/export means the engine
+        // itself misbehaved; those calls aren't expected to fail under normal operation.
+        StorageError::Fatal(e.to_string())
+    }
+}
+
+/// Maps a `StorageError` to the `tonic::Status` a gRPC handler should return. Fatal errors map to
+/// `internal`, but `KVService::get` exits the process on a fatal error before that status is
+/// sent, so only recoverable errors reach a caller; see `RaftNode::handle_storage_error` for the
+/// path proposals take through the run loop.
+pub fn to_status(err: &StorageError) -> tonic::Status {
+    match err {
+        StorageError::Fatal(msg) => tonic::Status::internal(msg.clone()),
+        StorageError::Recoverable(msg) => tonic::Status::failed_precondition(msg.clone()),
+    }
+}